@@ -4,15 +4,20 @@
 #![warn(missing_debug_implementations, missing_copy_implementations, trivial_casts, trivial_numeric_casts, unused_import_braces, unused_qualifications)]
 #![deny(unused_must_use, overflowing_literals)]
 
+extern crate ctrlc;
 extern crate num;
 extern crate image;
+extern crate rand;
 extern crate rayon;
 extern crate time;
 
 use std::io::Write;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use num::Complex;
+use rand::Rng;
 use rayon::prelude::*;
 use time::PreciseTime;
 
@@ -22,47 +27,294 @@ type GeneralResult<T> = Result<T, GeneralError>;
 #[cfg(test)]
 mod unit_tests;
 
-pub fn run(_args: Vec<String>) -> GeneralResult<()> {
-    if _args.len() != 5 {
-        writeln!(std::io::stderr(),
-                 "Usage: mandelbrot FILE PIXELS UPPERLEFT LOWERRIGHT")
-            .unwrap();
-        writeln!(std::io::stderr(),
-                 "Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
-                 _args[0])
-            .unwrap();
-        std::process::exit(1);
-    }
-    let bounds = parse_pair(&_args[2], 'x')
-        .expect("error parsing image dimensions");
-    let upper_left = parse_pair(&_args[3], ',')
-        .expect("error parsing upper left corner point");
-    let lower_right = parse_pair(&_args[4], ',')
-        .expect("error parsing lower right corner point");
-    let mut pixels = vec![0; bounds.0 * bounds.1];
+/// Reports completion progress for a long-running parallel render to
+/// stderr, unless `quiet` is set. Worker threads call `tick` once per unit
+/// of work; `Progress` tracks how many of `total` units have completed and
+/// prints an updated percentage/ETA line every `total / 100` units (every
+/// unit when `total` is below 100) and once more on completion.
+struct Progress {
+    done: AtomicUsize,
+    total: usize,
+    quiet: bool,
+    start: PreciseTime,
+}
 
-    let beg = PreciseTime::now();
-    // Scope of slicing up `pixels` into horizontal bands.
-    {
-        let bands: Vec<(usize, &mut [u8])> = pixels
-            .chunks_mut(bounds.0)
-            .enumerate()
-            .collect();
-        bands.into_par_iter()
-             .weight_max()
-             .for_each(|(i, band)| {
-                 let top = i;
-                 let band_bounds = (bounds.0, 1);
-                 let band_upper_left = pixel_to_point(bounds, (0, top),
-                                                      upper_left, lower_right);
-                 let band_lower_right = pixel_to_point(bounds, (bounds.0, top + 1),
-                                                       upper_left, lower_right);
-                 render(band, band_bounds, band_upper_left, band_lower_right);
-             });
+impl Progress {
+    fn new(total: usize, quiet: bool) -> Progress {
+        Progress { done: AtomicUsize::new(0), total: total, quiet: quiet, start: PreciseTime::now() }
+    }
+
+    /// Record that one more unit of work has finished.
+    fn tick(&self) {
+        if self.quiet || self.total == 0 {
+            return;
+        }
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let step = (self.total / 100).max(1);
+        if done % step == 0 || done == self.total {
+            let fraction = done as f64 / self.total as f64;
+            let elapsed_ms = self.start.to(PreciseTime::now()).num_milliseconds() as f64;
+            let eta_ms = if fraction > 0.0 { elapsed_ms / fraction - elapsed_ms } else { 0.0 };
+            writeln!(std::io::stderr(), "{:>3.0}% done, ETA {} ms",
+                     fraction * 100.0, eta_ms as i64)
+                .unwrap();
+        }
+    }
+}
+
+/// Install a Ctrl-C handler and return the flag it sets when triggered, so
+/// long-running parallel renders can check it between work items and abort
+/// early instead of running to completion uninterruptibly.
+fn install_cancel_handler() -> Arc<AtomicBool> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let handler_flag = cancelled.clone();
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst))
+        .expect("error installing Ctrl-C handler");
+    cancelled
+}
+
+/// Iteration limit used when the caller doesn't specify one on the command line.
+const DEFAULT_MAX_ITERATIONS: u32 = 1000;
+
+/// Number of Monte-Carlo samples a Buddhabrot/Nebulabrot pass draws when the
+/// caller doesn't specify a count on the command line.
+const DEFAULT_SAMPLES: u32 = 1_000_000;
+
+/// Which rendering technique `lib_main` should use to turn the requested
+/// region into pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// The classic per-pixel escape-time render performed by `render`.
+    EscapeTime,
+    /// The Buddhabrot technique performed by `buddhabrot`.
+    Buddhabrot,
+    /// Three Buddhabrot passes at different iteration limits, one per color
+    /// channel, performed by `nebulabrot`.
+    Nebulabrot,
+}
+
+impl FromStr for RenderMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "escape-time" => Ok(RenderMode::EscapeTime),
+            "buddhabrot" => Ok(RenderMode::Buddhabrot),
+            "nebulabrot" => Ok(RenderMode::Nebulabrot),
+            _ => Err(format!("unknown render mode '{}'", s)),
+        }
+    }
+}
+
+/// Supersampling factor used when the caller doesn't specify one on the
+/// command line, i.e. one sample per pixel.
+const DEFAULT_SUPERSAMPLE: u32 = 1;
+
+/// The fully-parsed, validated command-line arguments for `lib_main`.
+#[derive(Debug, Clone)]
+struct Cli {
+    output: String,
+    bounds: (usize, usize),
+    upper_left: (f64, f64),
+    lower_right: (f64, f64),
+    fractal: FractalKind,
+    max_iterations: u32,
+    palette: Palette,
+    mode: RenderMode,
+    samples: u32,
+    supersample: u32,
+    quiet: bool,
+}
+
+/// Compute the `(upper_left, lower_right)` corners of a viewport `zoom`
+/// units tall, centered on `center`, preserving `bounds`' aspect ratio. This
+/// is the `--center`/`--zoom` alternative to specifying corners directly.
+fn viewport_from_center(center: Complex<f64>, zoom: f64, bounds: (usize, usize))
+                        -> ((f64, f64), (f64, f64))
+{
+    let height = zoom;
+    let width = zoom * bounds.0 as f64 / bounds.1 as f64;
+    ((center.re - width / 2.0, center.im + height / 2.0),
+     (center.re + width / 2.0, center.im - height / 2.0))
+}
+
+/// Parse `args` (the full process argument list, including `argv[0]`) into a
+/// `Cli`, returning a descriptive error instead of panicking on missing or
+/// malformed input.
+///
+/// Recognized flags: `--size WIDTHxHEIGHT`, `--output FILE`, `--iterations
+/// N`, `--fractal KIND`, `--palette NAME`, `--mode NAME`, `--samples N`,
+/// `--supersample N`, `--quiet`, and either `--upper-left POINT` together
+/// with `--lower-right POINT`, or `--center POINT` together with `--zoom
+/// HEIGHT`, to describe the region of the complex plane to render.
+fn parse_args(args: &[String]) -> GeneralResult<Cli> {
+    let mut output = None;
+    let mut bounds = None;
+    let mut upper_left = None;
+    let mut lower_right = None;
+    let mut center = None;
+    let mut zoom = None;
+    let mut fractal = FractalKind::Mandelbrot;
+    let mut max_iterations = DEFAULT_MAX_ITERATIONS;
+    let mut palette = Palette::Linear;
+    let mut mode = RenderMode::EscapeTime;
+    let mut samples = DEFAULT_SAMPLES;
+    let mut supersample = DEFAULT_SUPERSAMPLE;
+    let mut quiet = false;
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--quiet" => quiet = true,
+            "--size" => {
+                let value = args.next().ok_or("--size requires a value, like 1000x750")?;
+                bounds = Some(parse_pair(value, 'x')
+                    .ok_or_else(|| format!("invalid --size '{}'", value))?);
+            }
+            "--output" => {
+                output = Some(args.next().ok_or("--output requires a file name")?.clone());
+            }
+            "--upper-left" => {
+                let value = args.next().ok_or("--upper-left requires a point, like -1.20,0.35")?;
+                upper_left = Some(parse_pair(value, ',')
+                    .ok_or_else(|| format!("invalid --upper-left '{}'", value))?);
+            }
+            "--lower-right" => {
+                let value = args.next().ok_or("--lower-right requires a point, like -1,0.20")?;
+                lower_right = Some(parse_pair(value, ',')
+                    .ok_or_else(|| format!("invalid --lower-right '{}'", value))?);
+            }
+            "--center" => {
+                let value = args.next().ok_or("--center requires a point, like -0.5,0")?;
+                center = Some(parse_complex(value)
+                    .ok_or_else(|| format!("invalid --center '{}'", value))?);
+            }
+            "--zoom" => {
+                let value = args.next().ok_or("--zoom requires a viewport height, like 2.0")?;
+                let parsed = value.parse::<f64>()
+                    .map_err(|_| format!("invalid --zoom '{}'", value))?;
+                if parsed <= 0.0 {
+                    return Err(format!("--zoom must be positive, got '{}'", value).into());
+                }
+                zoom = Some(parsed);
+            }
+            "--iterations" => {
+                let value = args.next().ok_or("--iterations requires a number")?;
+                max_iterations = value.parse()
+                    .map_err(|_| format!("invalid --iterations '{}'", value))?;
+            }
+            "--fractal" => {
+                let value = args.next().ok_or("--fractal requires a kind")?;
+                fractal = value.parse().map_err(|e| format!("invalid --fractal: {}", e))?;
+            }
+            "--palette" => {
+                let value = args.next().ok_or("--palette requires a name")?;
+                palette = value.parse().map_err(|e| format!("invalid --palette: {}", e))?;
+            }
+            "--mode" => {
+                let value = args.next().ok_or("--mode requires a name")?;
+                mode = value.parse().map_err(|e| format!("invalid --mode: {}", e))?;
+            }
+            "--samples" => {
+                let value = args.next().ok_or("--samples requires a number")?;
+                samples = value.parse().map_err(|_| format!("invalid --samples '{}'", value))?;
+            }
+            "--supersample" => {
+                let value = args.next().ok_or("--supersample requires a number")?;
+                supersample = value.parse()
+                    .map_err(|_| format!("invalid --supersample '{}'", value))?;
+            }
+            other => return Err(format!("unrecognized argument '{}'", other).into()),
+        }
     }
+
+    let output = output.ok_or("missing required --output FILE")?;
+    let bounds = bounds.ok_or("missing required --size WIDTHxHEIGHT")?;
+    let (upper_left, lower_right) = match (upper_left, lower_right, center, zoom) {
+        (Some(ul), Some(lr), None, None) => (ul, lr),
+        (None, None, Some(c), Some(z)) => viewport_from_center(c, z, bounds),
+        (None, None, None, None) =>
+            return Err("specify the region with --upper-left/--lower-right \
+                        or --center/--zoom".into()),
+        (Some(_), None, None, None) =>
+            return Err("--upper-left was given without --lower-right".into()),
+        (None, Some(_), None, None) =>
+            return Err("--lower-right was given without --upper-left".into()),
+        (None, None, Some(_), None) =>
+            return Err("--center was given without --zoom".into()),
+        (None, None, None, Some(_)) =>
+            return Err("--zoom was given without --center".into()),
+        _ =>
+            return Err("use either --upper-left/--lower-right or --center/--zoom, \
+                        not both".into()),
+    };
+
+    Ok(Cli {
+        output: output,
+        bounds: bounds,
+        upper_left: upper_left,
+        lower_right: lower_right,
+        fractal: fractal,
+        max_iterations: max_iterations,
+        palette: palette,
+        mode: mode,
+        samples: samples,
+        supersample: supersample,
+        quiet: quiet,
+    })
+}
+
+pub fn lib_main(_args: Vec<String>) -> GeneralResult<()> {
+    let cli = parse_args(&_args)?;
+    let cancel = install_cancel_handler();
+
+    let beg = PreciseTime::now();
+    let pixels = match cli.mode {
+        RenderMode::EscapeTime => {
+            let mut pixels = vec![0; cli.bounds.0 * cli.bounds.1 * 3];
+            let progress = Progress::new(cli.bounds.1, cli.quiet);
+            // Scope of slicing up `pixels` into horizontal bands.
+            {
+                let bands: Vec<(usize, &mut [u8])> = pixels
+                    .chunks_mut(cli.bounds.0 * 3)
+                    .enumerate()
+                    .collect();
+                bands.into_par_iter()
+                     .weight_max()
+                     .for_each(|(i, band)| {
+                         if cancel.load(Ordering::Relaxed) {
+                             return;
+                         }
+                         let top = i;
+                         let band_bounds = (cli.bounds.0, 1);
+                         let band_upper_left = pixel_to_point(cli.bounds, (0, top),
+                                                              cli.upper_left, cli.lower_right);
+                         let band_lower_right = pixel_to_point(cli.bounds, (cli.bounds.0, top + 1),
+                                                               cli.upper_left, cli.lower_right);
+                         render(band, band_bounds, band_upper_left, band_lower_right,
+                                cli.fractal, cli.max_iterations, cli.palette, cli.supersample);
+                         progress.tick();
+                     });
+            }
+            pixels
+        }
+        RenderMode::Buddhabrot => {
+            let accumulator = buddhabrot(cli.bounds, cli.upper_left, cli.lower_right,
+                                          cli.samples, cli.max_iterations, cli.quiet, &cancel);
+            let gray = normalize_accumulator(&accumulator, 0.5);
+            gray.into_iter().flat_map(|v| vec![v, v, v]).collect()
+        }
+        RenderMode::Nebulabrot =>
+            nebulabrot(cli.bounds, cli.upper_left, cli.lower_right, cli.samples, cli.quiet, &cancel),
+    };
     let end = PreciseTime::now();
     println!("{} ms", beg.to(end).num_milliseconds());
-    write_bitmap(&_args[1], &pixels, bounds).expect("error writing PNG file");
+
+    if cancel.load(Ordering::Relaxed) {
+        return Err("render cancelled".into());
+    }
+
+    write_bitmap(&cli.output, &pixels, cli.bounds, ColorType::RGB(8))?;
     Ok(())
 }
 
@@ -84,20 +336,107 @@ fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
         } }
 }
 
-/// Try to determine if `c` is in the Mandelbrot set, using at most `limit`
-/// iterations to decide.
+/// Parse the string `s` as a complex number, like `"-1.20,0.35"`, building on
+/// `parse_pair`.
+fn parse_complex(s: &str) -> Option<Complex<f64>> {
+    parse_pair(s, ',').map(|(re, im)| Complex { re: re, im: im })
+}
+
+/// The family of escape-time fractals this crate knows how to render.
+///
+/// `Mandelbrot` and `Multibrot` both iterate `z = z^power + c`; `Mandelbrot`
+/// is simply `Multibrot { power: 2 }` spelled out for clarity. `BurningShip`
+/// takes the absolute value of `z`'s components before squaring, which folds
+/// the lower half-plane onto the upper one and produces the ship-like shape
+/// the variant is named after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FractalKind {
+    Mandelbrot,
+    Multibrot { power: u32 },
+    BurningShip,
+}
+
+impl FractalKind {
+    /// Apply one iteration of this fractal's recurrence to `z`.
+    fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        match *self {
+            FractalKind::Mandelbrot => z * z + c,
+            FractalKind::Multibrot { power } => complex_powu(z, power) + c,
+            FractalKind::BurningShip => {
+                let folded = Complex { re: z.re.abs(), im: z.im.abs() };
+                folded * folded + c
+            }
+        }
+    }
+
+    /// The exponent of this fractal's recurrence (`z^degree + c`), i.e. `2`
+    /// for `Mandelbrot` and `BurningShip`, `power` for `Multibrot`. `render`
+    /// needs this to pick the right escape-rate divisor when smoothing.
+    fn degree(&self) -> u32 {
+        match *self {
+            FractalKind::Mandelbrot => 2,
+            FractalKind::Multibrot { power } => power,
+            FractalKind::BurningShip => 2,
+        }
+    }
+}
+
+impl FromStr for FractalKind {
+    type Err = String;
+
+    /// Parse `"mandelbrot"`, `"burning-ship"`, or `"multibrot-<power>"`
+    /// (e.g. `"multibrot-3"`) into a `FractalKind`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mandelbrot" => Ok(FractalKind::Mandelbrot),
+            "burning-ship" => Ok(FractalKind::BurningShip),
+            _ if s.starts_with("multibrot-") => {
+                let power = &s["multibrot-".len()..];
+                power.parse::<u32>()
+                    .map(|power| FractalKind::Multibrot { power: power })
+                    .map_err(|_| format!("invalid multibrot power in '{}'", s))
+            }
+            _ => Err(format!("unknown fractal kind '{}'", s)),
+        }
+    }
+}
+
+/// Raise `z` to the `power`th power by repeated multiplication.
+///
+/// `num`'s `Complex` has no built-in integer power in the version this crate
+/// depends on, and `power` is always small in practice, so a naive loop is
+/// both simplest and fast enough.
+fn complex_powu(z: Complex<f64>, power: u32) -> Complex<f64> {
+    let mut result = Complex { re: 1.0, im: 0.0 };
+    for _ in 0..power {
+        result = result * z;
+    }
+    result
+}
+
+/// Squared bailout radius `escapes` escapes beyond, i.e. `(1 << 16)^2`.
+///
+/// The classic radius-two bailout is enough to tell whether `c` is in the
+/// set, but the continuous coloring formula in `render` wants `z` to have
+/// grown well past that before we sample it, or the smoothed iteration
+/// count comes out visibly banded near the set's boundary.
+const BAILOUT_SQR: f64 = 4294967296.0; // (1 << 16) * (1 << 16)
+
+/// Try to determine if `c` is in the given fractal's set, using at most
+/// `limit` iterations to decide.
 ///
-/// If `c` is not a member, return `Some(i)`, where `i` is the number of
-/// iterations it took for `c` to leave the circle of radius two centered on the
-/// origin. If `c` seems to be a member (more precisely, if we reached the
-/// iteration limit without being able to prove that `c` is not a member),
-/// return `None`.
-fn escapes(c: Complex<f64>, limit: u32) -> Option<u32> {
+/// If `c` is not a member, return `Some((i, z))`, where `i` is the number of
+/// iterations it took for `c` to leave the bailout radius and `z` is the
+/// orbit value at that point (used by `render` for continuous coloring). If
+/// `c` seems to be a member (more precisely, if we reached the iteration
+/// limit without being able to prove that `c` is not a member), return
+/// `None`.
+fn escapes(c: Complex<f64>, limit: u32, kind: FractalKind) -> Option<(u32, Complex<f64>)> {
     let mut z = Complex { re: 0.0, im: 0.0 };
     for i in 0..limit {
-        z = z*z + c;
-        if z.norm_sqr() > 4.0 {
-            return Some(i);
+        z = kind.step(z, c);
+        if z.norm_sqr() > BAILOUT_SQR {
+            return Some((i, z));
         }
     }
     return None;
@@ -118,33 +457,286 @@ fn pixel_to_point(bounds: (usize, usize),
 {
     // It might be nicer to find the position of the *middle* of the pixel,
     // instead of its upper left corner, but this is easier to write tests for.
+    subpixel_to_point(bounds, pixel, (0.0, 0.0), upper_left, lower_right)
+}
+
+/// Return the point on the complex plane corresponding to a fractional
+/// position within a pixel's cell, where `frac` locates the sample as an
+/// offset in `0.0..1.0` from the pixel's upper left corner. `render` uses
+/// this to place sub-pixel samples when supersampling.
+fn subpixel_to_point(bounds: (usize, usize),
+                     pixel: (usize, usize),
+                     frac: (f64, f64),
+                     upper_left: (f64, f64),
+                     lower_right: (f64, f64))
+                     -> (f64, f64)
+{
     let (width, height) = (lower_right.0 - upper_left.0,
                            upper_left.1 - lower_right.1);
-    (upper_left.0 + pixel.0 as f64 * width  / bounds.0 as f64,
-     upper_left.1 - pixel.1 as f64 * height / bounds.1 as f64)
+    (upper_left.0 + (pixel.0 as f64 + frac.0) * width  / bounds.0 as f64,
+     upper_left.1 - (pixel.1 as f64 + frac.1) * height / bounds.1 as f64)
+}
+
+/// A color scheme for mapping a continuous escape-time value to an RGB color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    /// A plain black-to-white gradient.
+    Linear,
+    /// A full-saturation HSV rainbow, cycled once over the iteration range.
+    Rainbow,
+}
+
+impl Palette {
+    /// Map a normalized escape-time value `mu` (near `0.0` for points that
+    /// escape at once, approaching `max_iterations` near the set's boundary)
+    /// to an RGB color. Orbits that never
+    /// escape should be colored separately; this is only for escaped points.
+    fn color(&self, mu: f64, max_iterations: u32) -> [u8; 3] {
+        let t = (mu / max_iterations as f64).max(0.0).min(1.0);
+        match *self {
+            Palette::Linear => {
+                let v = (t * 255.0).round() as u8;
+                [v, v, v]
+            }
+            Palette::Rainbow => hsv_to_rgb(t * 360.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Palette::Linear),
+            "rainbow" => Ok(Palette::Rainbow),
+            _ => Err(format!("unknown palette '{}'", s)),
+        }
+    }
+}
+
+/// Convert an HSV color (`hue` in degrees, `saturation` and `value` in
+/// `0.0..=1.0`) to 8-bit RGB.
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> [u8; 3] {
+    let c = value * saturation;
+    let h = (hue % 360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    [((r + m) * 255.0).round() as u8,
+     ((g + m) * 255.0).round() as u8,
+     ((b + m) * 255.0).round() as u8]
+}
+
+/// Return the pixel in a bitmap of the given `bounds` that a point on the
+/// complex plane falls into, or `None` if the point lies outside the
+/// rectangle spanned by `upper_left` and `lower_right`. This is the inverse
+/// of `pixel_to_point`.
+fn point_to_pixel(bounds: (usize, usize),
+                   point: (f64, f64),
+                   upper_left: (f64, f64),
+                   lower_right: (f64, f64))
+                   -> Option<(usize, usize)>
+{
+    let (width, height) = (lower_right.0 - upper_left.0,
+                           upper_left.1 - lower_right.1);
+    let x = (point.0 - upper_left.0) / width * bounds.0 as f64;
+    let y = (upper_left.1 - point.1) / height * bounds.1 as f64;
+    if x < 0.0 || y < 0.0 || x >= bounds.0 as f64 || y >= bounds.1 as f64 {
+        return None;
+    }
+    Some((x as usize, y as usize))
+}
+
+/// Monte-Carlo sample `samples` points `c` uniformly over the rectangle
+/// spanned by `upper_left` and `lower_right`, iterating the standard
+/// `z = z^2 + c` recurrence up to `limit` steps. For every orbit that
+/// escapes the bailout radius, replay its trajectory and increment the
+/// accumulator cell (mapped back to a pixel via `point_to_pixel`) of every
+/// point it visited; orbits that never escape contribute nothing. Returns a
+/// `bounds.0 * bounds.1` grid of visit counts.
+///
+/// Sampling is split across rayon's thread pool with each worker folding
+/// into a private accumulator grid; rayon's reduce then sums those grids
+/// pairwise, so workers never contend over a shared pixel count.
+fn buddhabrot(bounds: (usize, usize),
+              upper_left: (f64, f64),
+              lower_right: (f64, f64),
+              samples: u32,
+              limit: u32,
+              quiet: bool,
+              cancel: &AtomicBool)
+              -> Vec<u32>
+{
+    // `gen_range` panics unless `low < high`, but `escapes`-based rendering
+    // tolerates corners given in either order, so normalize here too rather
+    // than trust the caller's orientation.
+    let re_range = (upper_left.0.min(lower_right.0), upper_left.0.max(lower_right.0));
+    let im_range = (lower_right.1.min(upper_left.1), lower_right.1.max(upper_left.1));
+
+    let progress = Progress::new(samples as usize, quiet);
+    (0 .. samples).into_par_iter()
+        .fold(|| vec![0u32; bounds.0 * bounds.1], |mut acc, _| {
+            if cancel.load(Ordering::Relaxed) {
+                return acc;
+            }
+            let mut rng = rand::thread_rng();
+            let c = Complex {
+                re: rng.gen_range(re_range.0, re_range.1),
+                im: rng.gen_range(im_range.0, im_range.1),
+            };
+
+            let mut orbit = Vec::with_capacity(limit as usize);
+            let mut z = Complex { re: 0.0, im: 0.0 };
+            let mut escaped = false;
+            for _ in 0 .. limit {
+                z = z * z + c;
+                orbit.push(z);
+                if z.norm_sqr() > BAILOUT_SQR {
+                    escaped = true;
+                    break;
+                }
+            }
+            if escaped {
+                for point in orbit {
+                    if let Some(pixel) = point_to_pixel(bounds, (point.re, point.im),
+                                                         upper_left, lower_right) {
+                        acc[pixel.1 * bounds.0 + pixel.0] += 1;
+                    }
+                }
+            }
+            progress.tick();
+            acc
+        })
+        .reduce(|| vec![0u32; bounds.0 * bounds.1], |mut a, b| {
+            for (cell, other) in a.iter_mut().zip(b.into_iter()) {
+                *cell += other;
+            }
+            a
+        })
 }
 
-/// Render a rectangle of the Mandelbrot set into a buffer of pixels.
+/// Normalize a Buddhabrot accumulator grid to 8-bit grayscale, dividing by
+/// the highest visit count and applying a gamma curve so lightly-visited
+/// cells remain visible instead of being crushed to black.
+fn normalize_accumulator(accumulator: &[u32], gamma: f64) -> Vec<u8> {
+    let max = accumulator.iter().cloned().max().unwrap_or(0).max(1) as f64;
+    accumulator.iter()
+        .map(|&count| ((count as f64 / max).powf(gamma) * 255.0).round() as u8)
+        .collect()
+}
+
+/// Iteration limits for the three Buddhabrot passes `nebulabrot` combines
+/// into the red, green, and blue channels respectively.
+const NEBULABROT_LIMITS: [u32; 3] = [50, 500, 5000];
+
+/// Render a "Nebulabrot": three independent Buddhabrot passes at the
+/// iteration limits in `NEBULABROT_LIMITS`, each normalized and written into
+/// its own color channel. Short-orbit samples dominate one channel and
+/// long-orbit samples another, giving the combined image its characteristic
+/// color separation.
+fn nebulabrot(bounds: (usize, usize),
+              upper_left: (f64, f64),
+              lower_right: (f64, f64),
+              samples: u32,
+              quiet: bool,
+              cancel: &AtomicBool)
+              -> Vec<u8>
+{
+    let channels: Vec<Vec<u8>> = NEBULABROT_LIMITS.iter()
+        .map(|&limit| {
+            let accumulator = buddhabrot(bounds, upper_left, lower_right, samples, limit, quiet, cancel);
+            normalize_accumulator(&accumulator, 0.5)
+        })
+        .collect();
+
+    let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+    for i in 0 .. bounds.0 * bounds.1 {
+        pixels[i * 3]     = channels[0][i];
+        pixels[i * 3 + 1] = channels[1][i];
+        pixels[i * 3 + 2] = channels[2][i];
+    }
+    pixels
+}
+
+/// Render a rectangle of the given fractal's set into a buffer of RGB pixels.
     ///
     /// The `bounds` argument gives the width and height of the buffer `pixels`,
-    /// which holds one grayscale pixel per byte. The `upper_left` and `lower_right`
+    /// which holds one RGB pixel per three bytes. The `upper_left` and `lower_right`
     /// arguments specify points on the complex plane corresponding to the upper
-    /// left and lower right corners of the pixel buffer.
+    /// left and lower right corners of the pixel buffer. `kind` selects which
+    /// fractal family's recurrence `escapes` should use, `max_iterations` bounds
+    /// how long `escapes` may search, and `palette` maps the smoothed escape
+    /// time to a color. `supersample` evaluates each pixel on a `supersample *
+    /// supersample` subgrid and averages the results, which softens aliasing
+    /// along the set's boundary; pass `1` to sample once per pixel as before.
 fn render(pixels: &mut [u8],
           bounds: (usize, usize),
           upper_left: (f64, f64),
-          lower_right: (f64, f64))
+          lower_right: (f64, f64),
+          kind: FractalKind,
+          max_iterations: u32,
+          palette: Palette,
+          supersample: u32)
 {
-    assert!(pixels.len() == bounds.0 * bounds.1);
+    assert!(pixels.len() == bounds.0 * bounds.1 * 3);
+    let sample_count = (supersample * supersample) as f64;
     for r in 0 .. bounds.1 {
         for c in 0 .. bounds.0 {
-            let point = pixel_to_point(bounds, (c, r),
-                                       upper_left, lower_right);
-            pixels[r * bounds.0 + c] =
-                match escapes(Complex { re: point.0, im: point.1 }, 255) {
-                    None => 0,
-                    Some(count) => 255 - count as u8
-                };
+            let mut sum = [0f64; 3];
+            for sr in 0 .. supersample {
+                for sc in 0 .. supersample {
+                    let frac = (sc as f64 / supersample as f64, sr as f64 / supersample as f64);
+                    let point = subpixel_to_point(bounds, (c, r), frac,
+                                                  upper_left, lower_right);
+                    let color = match escapes(Complex { re: point.0, im: point.1 }, max_iterations, kind) {
+                        None => [0, 0, 0],
+                        Some((count, z)) => {
+                            // Smoothed iteration count: mu = n + 1 - ln(ln(|z|)) / ln(degree).
+                            // The divisor is the recurrence's escape rate, ln(2) for the
+                            // quadratic Mandelbrot/Burning Ship but ln(power) for Multibrot.
+                            let mu = count as f64 + 1.0
+                                - z.norm().ln().ln() / (kind.degree() as f64).ln();
+                            palette.color(mu, max_iterations)
+                        }
+                    };
+                    for channel in 0 .. 3 {
+                        sum[channel] += color[channel] as f64;
+                    }
+                }
+            }
+            let offset = (r * bounds.0 + c) * 3;
+            for channel in 0 .. 3 {
+                pixels[offset + channel] = (sum[channel] / sample_count).round() as u8;
+            }
+        }
+    }
+}
+
+/// The file formats `write_bitmap` knows how to produce, inferred from the
+/// output filename's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Png,
+    Pgm,
+    Ppm,
+}
+
+impl OutputFormat {
+    /// Infer the output format from `filename`'s extension (`.png`, `.pgm`,
+    /// or `.ppm`), defaulting to PNG for anything else.
+    fn from_filename(filename: &str) -> OutputFormat {
+        match filename.rsplit('.').next() {
+            Some(ext) if ext.eq_ignore_ascii_case("pgm") => OutputFormat::Pgm,
+            Some(ext) if ext.eq_ignore_ascii_case("ppm") => OutputFormat::Ppm,
+            _ => OutputFormat::Png,
         }
     }
 }
@@ -152,15 +744,61 @@ fn render(pixels: &mut [u8],
 use image::ColorType;
 use image::png::PNGEncoder;
 use std::fs::File;
-/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to the
-/// file named `filename`.
-fn write_bitmap(filename: &str, pixels: &[u8], bounds: (usize, usize))
+/// Write the buffer `pixels`, whose dimensions are given by `bounds` and whose
+/// pixel format is given by `color`, to the file named `filename`. The format
+/// (PNG, or dependency-free PNM) is inferred from `filename`'s extension; for
+/// PNM, the extension also picks the channel count (`.pgm` downconverts RGB
+/// input to grayscale, `.ppm` upconverts grayscale input to RGB), so the two
+/// stay distinguishable regardless of `color`.
+fn write_bitmap(filename: &str, pixels: &[u8], bounds: (usize, usize), color: ColorType)
                 -> Result<(), std::io::Error>
 {
-    let output = File::create(filename)?;
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(&pixels,
-                   bounds.0 as u32, bounds.1 as u32,
-                   ColorType::Gray(8))?;
+    match OutputFormat::from_filename(filename) {
+        OutputFormat::Png => {
+            let output = File::create(filename)?;
+            let encoder = PNGEncoder::new(output);
+            encoder.encode(&pixels,
+                           bounds.0 as u32, bounds.1 as u32,
+                           color)?;
+            Ok(())
+        }
+        OutputFormat::Pgm => write_pnm(filename, &into_gray(pixels, color), bounds, "P5"),
+        OutputFormat::Ppm => write_pnm(filename, &into_rgb(pixels, color), bounds, "P6"),
+    }
+}
+
+/// Convert `pixels` (encoded as `color`) into an 8-bit RGB buffer, tripling
+/// each sample into R/G/B if `pixels` is already grayscale.
+fn into_rgb(pixels: &[u8], color: ColorType) -> Vec<u8> {
+    match color {
+        ColorType::RGB(8) => pixels.to_vec(),
+        ColorType::Gray(8) => pixels.iter().flat_map(|&v| vec![v, v, v]).collect(),
+        _ => panic!("PNM output only supports 8-bit grayscale or RGB"),
+    }
+}
+
+/// Convert `pixels` (encoded as `color`) into an 8-bit grayscale buffer,
+/// downconverting RGB via the standard luminance weights.
+fn into_gray(pixels: &[u8], color: ColorType) -> Vec<u8> {
+    match color {
+        ColorType::Gray(8) => pixels.to_vec(),
+        ColorType::RGB(8) => pixels.chunks(3)
+            .map(|rgb| (0.299 * rgb[0] as f64 + 0.587 * rgb[1] as f64 + 0.114 * rgb[2] as f64)
+                .round() as u8)
+            .collect(),
+        _ => panic!("PNM output only supports 8-bit grayscale or RGB"),
+    }
+}
+
+/// Write `pixels` as a binary PNM file with the given `magic` (`"P5"` for
+/// PGM, `"P6"` for PPM). This writes the classic `magic\nwidth
+/// height\n255\n` header followed by the raw pixel bytes directly, with no
+/// dependency on the `image` crate's codecs.
+fn write_pnm(filename: &str, pixels: &[u8], bounds: (usize, usize), magic: &str)
+             -> Result<(), std::io::Error>
+{
+    let mut output = File::create(filename)?;
+    write!(output, "{}\n{} {}\n255\n", magic, bounds.0, bounds.1)?;
+    output.write_all(pixels)?;
     Ok(())
 }
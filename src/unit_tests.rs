@@ -14,9 +14,95 @@ fn test_parse_pair() {
     assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
 }
 
+#[test]
+fn test_parse_complex() {
+    assert_eq!(parse_complex("-1.20,0.35"), Some(Complex { re: -1.20, im: 0.35 }));
+    assert_eq!(parse_complex(",0.35"), None);
+    assert_eq!(parse_complex("-1.20,"), None);
+}
+
+#[test]
+fn test_fractal_kind_degree() {
+    assert_eq!(FractalKind::Mandelbrot.degree(), 2);
+    assert_eq!(FractalKind::BurningShip.degree(), 2);
+    assert_eq!(FractalKind::Multibrot { power: 5 }.degree(), 5);
+}
+
+#[test]
+fn test_fractal_kind_from_str() {
+    assert_eq!("mandelbrot".parse(), Ok(FractalKind::Mandelbrot));
+    assert_eq!("burning-ship".parse(), Ok(FractalKind::BurningShip));
+    assert_eq!("multibrot-3".parse(), Ok(FractalKind::Multibrot { power: 3 }));
+    assert!("multibrot-".parse::<FractalKind>().is_err());
+    assert!("nonsense".parse::<FractalKind>().is_err());
+}
+
+#[test]
+fn test_palette_from_str() {
+    assert_eq!("linear".parse(), Ok(Palette::Linear));
+    assert_eq!("rainbow".parse(), Ok(Palette::Rainbow));
+    assert!("nonsense".parse::<Palette>().is_err());
+}
+
+#[test]
+fn test_hsv_to_rgb() {
+    assert_eq!(hsv_to_rgb(0.0, 1.0, 1.0), [255, 0, 0]);
+    assert_eq!(hsv_to_rgb(120.0, 1.0, 1.0), [0, 255, 0]);
+    assert_eq!(hsv_to_rgb(240.0, 1.0, 1.0), [0, 0, 255]);
+}
+
 #[test]
 fn test_pixel_to_point() {
     assert_eq!(pixel_to_point((100, 100), (25, 75),
                               (-1.0, 1.0), (1.0, -1.0)),
     (-0.5, -0.5));
 }
+
+#[test]
+fn test_subpixel_to_point() {
+    assert_eq!(subpixel_to_point((100, 100), (25, 75), (0.0, 0.0),
+                                 (-1.0, 1.0), (1.0, -1.0)),
+    pixel_to_point((100, 100), (25, 75), (-1.0, 1.0), (1.0, -1.0)));
+    assert_eq!(subpixel_to_point((100, 100), (25, 75), (0.5, 0.5),
+                                 (-1.0, 1.0), (1.0, -1.0)),
+    (-0.49, -0.51));
+}
+
+#[test]
+fn test_point_to_pixel() {
+    assert_eq!(point_to_pixel((100, 100), (-0.5, -0.5),
+                              (-1.0, 1.0), (1.0, -1.0)),
+    Some((25, 75)));
+    assert_eq!(point_to_pixel((100, 100), (5.0, 5.0),
+                              (-1.0, 1.0), (1.0, -1.0)),
+    None);
+}
+
+#[test]
+fn test_into_gray_downconverts_rgb() {
+    assert_eq!(into_gray(&[255, 255, 255, 0, 0, 0], ColorType::RGB(8)), vec![255, 0]);
+    assert_eq!(into_gray(&[128, 64], ColorType::Gray(8)), vec![128, 64]);
+}
+
+#[test]
+fn test_into_rgb_upconverts_gray() {
+    assert_eq!(into_rgb(&[128, 64], ColorType::Gray(8)),
+               vec![128, 128, 128, 64, 64, 64]);
+    assert_eq!(into_rgb(&[1, 2, 3], ColorType::RGB(8)), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_output_format_from_filename() {
+    assert_eq!(OutputFormat::from_filename("mandel.png"), OutputFormat::Png);
+    assert_eq!(OutputFormat::from_filename("mandel.pgm"), OutputFormat::Pgm);
+    assert_eq!(OutputFormat::from_filename("mandel.PPM"), OutputFormat::Ppm);
+    assert_eq!(OutputFormat::from_filename("mandel"), OutputFormat::Png);
+}
+
+#[test]
+fn test_render_mode_from_str() {
+    assert_eq!("escape-time".parse(), Ok(RenderMode::EscapeTime));
+    assert_eq!("buddhabrot".parse(), Ok(RenderMode::Buddhabrot));
+    assert_eq!("nebulabrot".parse(), Ok(RenderMode::Nebulabrot));
+    assert!("nonsense".parse::<RenderMode>().is_err());
+}